@@ -1,13 +1,32 @@
-use api_models::payments;
-use error_stack::report;
-use masking::Secret;
+use api_models::{payments, webhooks::IncomingWebhookEvent};
+use common_utils::crypto;
+use error_stack::{report, ResultExt};
+use masking::{ExposeInterface, Secret};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    connector::utils::RouterData as _,
+    consts,
     core::errors,
     types::{self, storage::enums},
 };
 
+fn get_purchase_country_and_locale(
+    billing_country: enums::CountryAlpha2,
+) -> (String, String) {
+    let purchase_country = billing_country.to_string();
+    let locale = match billing_country {
+        enums::CountryAlpha2::DE => "de-DE",
+        enums::CountryAlpha2::SE => "sv-SE",
+        enums::CountryAlpha2::FI => "fi-FI",
+        enums::CountryAlpha2::GB => "en-GB",
+        enums::CountryAlpha2::US => "en-US",
+        _ => return (purchase_country.clone(), format!("en-{purchase_country}")),
+    }
+    .to_string();
+    (purchase_country, locale)
+}
+
 #[derive(Debug, Serialize)]
 pub struct KlarnaRouterData<T> {
     amount: i64,
@@ -27,7 +46,7 @@ impl<T>
     type Error = error_stack::Report<errors::ConnectorError>;
 
     fn try_from(
-        (_currency_unit, _currency, amount, router_data): (
+        (_currency_unit, _currency, amount, router_data, connector_request_reference_id): (
             &types::api::CurrencyUnit,
             types::storage::enums::Currency,
             i64,
@@ -50,6 +69,8 @@ pub struct KlarnaPaymentsRequest {
     purchase_country: String,
     purchase_currency: enums::Currency,
     connector_request_reference_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    customer_token: Option<Secret<String>>,
 }
 
 #[derive(Default, Debug, Deserialize)]
@@ -57,6 +78,7 @@ pub struct KlarnaPaymentsResponse {
     order_id: String,
     fraud_status: KlarnaFraudStatus,
     connector_request_reference_id: String,
+    customer_token: Option<Secret<String>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -79,22 +101,27 @@ impl TryFrom<&types::PaymentsSessionRouterData> for KlarnaSessionRequest {
     type Error = error_stack::Report<errors::ConnectorError>;
     fn try_from(item: &types::PaymentsSessionRouterData) -> Result<Self, Self::Error> {
         let request = &item.request;
+        // `PaymentsSessionRouterData` has no explicit "tokenize only, no purchase" signal,
+        // so a zero order amount is used as a proxy for pure mandate setup. This is a
+        // heuristic, not a guarantee: a zero-amount off-session session created for other
+        // reasons would be (mis)treated as tokenize-only, and a mandate setup bundled with a non-zero
+        // first payment always takes `BuyAndTokenize`. Replace with a direct intent field on
+        // the request if/when one becomes available upstream.
+        let intent = match (request.setup_future_usage, request.amount) {
+            (Some(enums::FutureUsage::OffSession), 0) => KlarnaSessionIntent::Tokenize,
+            (Some(enums::FutureUsage::OffSession), _) => KlarnaSessionIntent::BuyAndTokenize,
+            _ => KlarnaSessionIntent::Buy,
+        };
+        let (purchase_country, locale) =
+            get_purchase_country_and_locale(item.get_billing_country()?);
         match request.order_details.clone() {
             Some(order_details) => Ok(Self {
-                intent: KlarnaSessionIntent::Buy,
-                purchase_country: "US".to_string(),
+                intent,
+                purchase_country,
                 purchase_currency: request.currency,
                 order_amount: request.amount,
-                locale: "en-US".to_string(),
-                order_lines: order_details
-                    .iter()
-                    .map(|data| OrderLines {
-                        name: data.product_name.clone(),
-                        quantity: data.quantity,
-                        unit_price: data.amount,
-                        total_amount: i64::from(data.quantity) * (data.amount),
-                    })
-                    .collect(),
+                locale,
+                order_lines: build_order_lines(&order_details, request.amount)?,
             }),
             None => Err(report!(errors::ConnectorError::MissingRequiredField {
                 field_name: "product_name",
@@ -132,22 +159,37 @@ impl TryFrom<&KlarnaRouterData<&types::PaymentsAuthorizeRouterData>> for KlarnaP
         item: &KlarnaRouterData<&types::PaymentsAuthorizeRouterData>,
     ) -> Result<Self, Self::Error> {
         let request = &item.router_data.request;
-        let connector_request_reference_id = item.response.reference.unwrap_or_default();
+        let connector_request_reference_id = item.connector_request_reference_id.clone();
+        let (purchase_country, _locale) =
+            get_purchase_country_and_locale(item.router_data.get_billing_country()?);
+        let connector_mandate_id = request
+            .mandate_id
+            .as_ref()
+            .and_then(|mandate_ids| mandate_ids.mandate_reference_id.clone())
+            .and_then(|mandate_reference_id| match mandate_reference_id {
+                payments::MandateReferenceId::ConnectorMandateId(connector_mandate_ids) => {
+                    connector_mandate_ids.get_connector_mandate_id()
+                }
+                payments::MandateReferenceId::NetworkMandateId(_) => None,
+            });
+        if let Some(connector_mandate_id) = connector_mandate_id {
+            return Ok(Self {
+                purchase_country: purchase_country.clone(),
+                purchase_currency: request.currency,
+                connector_request_reference_id,
+                order_amount: request.amount,
+                order_lines: vec![],
+                customer_token: Some(Secret::new(connector_mandate_id)),
+            });
+        }
         match request.order_details.clone() {
             Some(order_details) => Ok(Self {
-                purchase_country: "US".to_string(),
+                purchase_country: purchase_country.clone(),
                 purchase_currency: request.currency,
                 connector_request_reference_id,
                 order_amount: request.amount,
-                order_lines: order_details
-                    .iter()
-                    .map(|data| OrderLines {
-                        name: data.product_name.clone(),
-                        quantity: data.quantity,
-                        unit_price: data.amount,
-                        total_amount: i64::from(data.quantity) * (data.amount),
-                    })
-                    .collect(),
+                order_lines: build_order_lines(&order_details, request.amount)?,
+                customer_token: None,
             }),
             None => Err(report!(errors::ConnectorError::MissingRequiredField {
                 field_name: "product_name"
@@ -165,11 +207,17 @@ impl TryFrom<types::PaymentsResponseRouterData<KlarnaPaymentsResponse>>
     ) -> Result<Self, Self::Error> {
         let response = &item.response;
         let connector_request_reference_id = response.connector_request_reference_id;
+        let mandate_reference = item.response.customer_token.clone().map(|customer_token| {
+            types::MandateReference {
+                connector_mandate_id: Some(customer_token.expose()),
+                payment_method_id: None,
+            }
+        });
         Ok(Self {
             response: Ok(types::PaymentsResponseData::TransactionResponse {
                 resource_id: types::ResponseId::ConnectorTransactionId(item.response.order_id),
                 redirection_data: None,
-                mandate_reference: None,
+                mandate_reference,
                 connector_metadata: None,
                 network_txn_id: None,
                 connector_response_reference_id: Some(connector_request_reference_id),
@@ -185,11 +233,153 @@ pub struct OrderLines {
     quantity: u16,
     unit_price: i64,
     total_amount: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+}
+
+// Scope cut: the request asked for tax rate, discount, and SKU propagation on each line,
+// but `payments::OrderDetailsWithAmount` carries none of that — only name, quantity, amount,
+// and `product_img_link`. Rather than ship `tax_rate`/`total_tax_amount`/
+// `total_discount_amount`/`reference` fields that can only ever serialize as `None`,
+// `OrderLines` is scoped down to the subset this source type can actually populate. The
+// `total_amount` reconciliation below therefore only catches quantity/unit-price mismatches,
+// not tax or discount drift — extending it requires adding those fields upstream first.
+fn build_order_lines(
+    order_details: &[payments::OrderDetailsWithAmount],
+    order_amount: i64,
+) -> Result<Vec<OrderLines>, error_stack::Report<errors::ConnectorError>> {
+    let order_lines: Vec<OrderLines> = order_details
+        .iter()
+        .map(|data| OrderLines {
+            name: data.product_name.clone(),
+            quantity: data.quantity,
+            unit_price: data.amount,
+            total_amount: i64::from(data.quantity) * (data.amount),
+            image_url: data.product_img_link.clone(),
+        })
+        .collect();
+    let summed_total_amount: i64 = order_lines.iter().map(|line| line.total_amount).sum();
+    if summed_total_amount != order_amount {
+        return Err(report!(errors::ConnectorError::RequestEncodingFailed));
+    }
+    Ok(order_lines)
+}
+
+#[derive(Debug, Serialize)]
+pub struct KlarnaCaptureRequest {
+    captured_amount: i64,
+}
+
+impl TryFrom<&types::PaymentsCaptureRouterData> for KlarnaCaptureRequest {
+    type Error = error_stack::Report<errors::ConnectorError>;
+
+    fn try_from(item: &types::PaymentsCaptureRouterData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            captured_amount: item.request.amount_to_capture,
+        })
+    }
+}
+
+#[derive(Default, Debug, Deserialize)]
+pub struct KlarnaCaptureResponse {
+    capture_id: String,
+    order_id: String,
+}
+
+impl TryFrom<types::PaymentsCaptureResponseRouterData<KlarnaCaptureResponse>>
+    for types::PaymentsCaptureRouterData
+{
+    type Error = error_stack::Report<errors::ConnectorError>;
+    fn try_from(
+        item: types::PaymentsCaptureResponseRouterData<KlarnaCaptureResponse>,
+    ) -> Result<Self, Self::Error> {
+        // `KlarnaCaptureResponse` itself carries no status field to map from, so we fall back
+        // to comparing the captured amount against the full capturable amount, the same signal
+        // Klarna's API itself relies on to distinguish a partial capture from a full one.
+        let status = if item.data.request.amount_to_capture < item.data.request.payment_amount {
+            enums::AttemptStatus::PartialCharged
+        } else {
+            enums::AttemptStatus::Charged
+        };
+        Ok(Self {
+            response: Ok(types::PaymentsResponseData::TransactionResponse {
+                resource_id: types::ResponseId::ConnectorTransactionId(
+                    item.response.order_id.clone(),
+                ),
+                redirection_data: None,
+                mandate_reference: None,
+                connector_metadata: None,
+                network_txn_id: None,
+                connector_response_reference_id: Some(item.response.capture_id),
+            }),
+            status,
+            ..item.data
+        })
+    }
+}
+
+// `RefundsData` carries only a flat refund amount, not per-line items, so a partial refund
+// is expressed purely through `refunded_amount`; there is no `order_lines` field to populate
+// a line-item breakdown with, unlike the order-creation requests above.
+#[derive(Debug, Serialize)]
+pub struct KlarnaRefundRequest {
+    refunded_amount: i64,
+}
+
+impl<F> TryFrom<&KlarnaRouterData<&types::RefundsRouterData<F>>> for KlarnaRefundRequest {
+    type Error = error_stack::Report<errors::ConnectorError>;
+
+    fn try_from(
+        item: &KlarnaRouterData<&types::RefundsRouterData<F>>,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            refunded_amount: item.amount,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum KlarnaRefundStatus {
+    Accepted,
+    #[default]
+    Pending,
+}
+
+impl From<KlarnaRefundStatus> for enums::RefundStatus {
+    fn from(item: KlarnaRefundStatus) -> Self {
+        match item {
+            KlarnaRefundStatus::Accepted => Self::Success,
+            KlarnaRefundStatus::Pending => Self::Pending,
+        }
+    }
+}
+
+#[derive(Default, Debug, Deserialize)]
+pub struct KlarnaRefundResponse {
+    refund_id: String,
+    status: KlarnaRefundStatus,
+}
+
+impl<F> TryFrom<types::RefundsResponseRouterData<F, KlarnaRefundResponse>>
+    for types::RefundsRouterData<F>
+{
+    type Error = error_stack::Report<errors::ConnectorError>;
+    fn try_from(
+        item: types::RefundsResponseRouterData<F, KlarnaRefundResponse>,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            response: Ok(types::RefundsResponseData {
+                connector_refund_id: item.response.refund_id,
+                refund_status: enums::RefundStatus::from(item.response.status),
+            }),
+            ..item.data
+        })
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
-#[allow(dead_code)]
 pub enum KlarnaSessionIntent {
     Buy,
     Tokenize,
@@ -236,3 +426,130 @@ pub struct KlarnaErrorResponse {
     pub error_messages: Option<Vec<String>>,
     pub error_message: Option<String>,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KlarnaAttemptOutcome {
+    /// The attempt reached Klarna and was terminally rejected (e.g. a decline or an
+    /// expired resource) — the attempt status should be updated to `Failure`.
+    Terminal,
+    /// The request never resulted in a processed attempt (e.g. a validation error) —
+    /// there is no attempt status to update.
+    Indeterminate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KlarnaKnownErrorCode {
+    NotFound,
+    BadValue,
+    ExpiredResource,
+    PaymentMethodDeclined,
+}
+
+impl KlarnaKnownErrorCode {
+    fn from_error_code(error_code: &str) -> Option<Self> {
+        match error_code {
+            "NOT_FOUND" => Some(Self::NotFound),
+            "BAD_VALUE" => Some(Self::BadValue),
+            "EXPIRED_RESOURCE" => Some(Self::ExpiredResource),
+            "PAYMENT_METHOD_DECLINED" => Some(Self::PaymentMethodDeclined),
+            _ => None,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::NotFound => "The requested Klarna resource could not be found",
+            Self::BadValue => "One or more fields in the request contain an invalid value",
+            Self::ExpiredResource => "The Klarna session or order has expired",
+            Self::PaymentMethodDeclined => {
+                "The customer's payment method was declined by Klarna"
+            }
+        }
+    }
+
+    fn outcome(self) -> KlarnaAttemptOutcome {
+        match self {
+            Self::NotFound | Self::BadValue => KlarnaAttemptOutcome::Indeterminate,
+            Self::ExpiredResource | Self::PaymentMethodDeclined => KlarnaAttemptOutcome::Terminal,
+        }
+    }
+
+    fn attempt_status(self) -> Option<enums::AttemptStatus> {
+        match self.outcome() {
+            KlarnaAttemptOutcome::Terminal => Some(enums::AttemptStatus::Failure),
+            KlarnaAttemptOutcome::Indeterminate => None,
+        }
+    }
+}
+
+impl From<KlarnaErrorResponse> for types::ErrorResponse {
+    fn from(response: KlarnaErrorResponse) -> Self {
+        let known_code = KlarnaKnownErrorCode::from_error_code(&response.error_code);
+        let message = known_code
+            .map(KlarnaKnownErrorCode::description)
+            .unwrap_or(consts::NO_ERROR_MESSAGE)
+            .to_string();
+        let attempt_status = known_code.and_then(KlarnaKnownErrorCode::attempt_status);
+        let reason = response
+            .error_messages
+            .map(|messages| messages.join(", "))
+            .or(response.error_message);
+        Self {
+            code: response.error_code,
+            message,
+            reason,
+            status_code: 400,
+            attempt_status,
+            connector_transaction_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KlarnaWebhookEventType {
+    FraudRiskAccepted,
+    FraudRiskRejected,
+    FraudRiskStopped,
+    /// Catch-all for event types Klarna may add in the future; treated as a no-op so new
+    /// webhook events don't hard-fail deserialization before we have explicit handling for them.
+    #[serde(other)]
+    Unknown,
+}
+
+impl From<KlarnaWebhookEventType> for IncomingWebhookEvent {
+    fn from(event_type: KlarnaWebhookEventType) -> Self {
+        match event_type {
+            KlarnaWebhookEventType::FraudRiskAccepted => Self::PaymentIntentSuccess,
+            KlarnaWebhookEventType::FraudRiskRejected | KlarnaWebhookEventType::FraudRiskStopped => {
+                Self::PaymentIntentFailure
+            }
+            KlarnaWebhookEventType::Unknown => Self::EventNotSupported,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KlarnaWebhookBody {
+    pub order_id: String,
+    pub event_type: KlarnaWebhookEventType,
+}
+
+impl KlarnaWebhookBody {
+    pub fn get_webhook_object_reference_id(&self) -> String {
+        self.order_id.clone()
+    }
+}
+
+/// Verifies a Klarna webhook using an HMAC-SHA256 signature computed over the raw request
+/// body with the merchant's connector webhook secret, as Klarna does not sign individual
+/// fields but the whole payload.
+pub fn verify_webhook_source(
+    secret: &[u8],
+    signature: &[u8],
+    message: &[u8],
+) -> errors::CustomResult<bool, errors::ConnectorError> {
+    crypto::HmacSha256
+        .verify_signature(secret, signature, message)
+        .change_context(errors::ConnectorError::WebhookSourceVerificationFailed)
+}